@@ -0,0 +1,531 @@
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::Value;
+use thiserror::Error;
+
+use super::options::{Model, PerExecutor, PerInvocation, Provider};
+use super::output::Output;
+
+const OPENAI_CHAT_COMPLETIONS_URL: &str = "https://api.openai.com/v1/chat/completions";
+const ANTHROPIC_MESSAGES_URL: &str = "https://api.anthropic.com/v1/messages";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const GEMINI_API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta/models";
+
+/// Errors that can occur while talking to a provider's chat endpoint.
+#[derive(Debug, Error)]
+pub enum ExecutorError {
+    #[error("request to the provider failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("failed to parse the provider's response: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("{0:?} does not support this operation yet")]
+    UnsupportedForProvider(Provider),
+}
+
+/// A single incremental delta parsed out of a `chat.completion.chunk` SSE event.
+#[derive(Debug, Deserialize)]
+struct ChunkDelta {
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkChoice {
+    delta: ChunkDelta,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chunk {
+    choices: Vec<ChunkChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolCallFunction {
+    name: String,
+    arguments: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolCall {
+    function: ToolCallFunction,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCall>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: Message,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionResponse {
+    choices: Vec<Choice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicContentBlock {
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicResponse {
+    content: Vec<AnthropicContentBlock>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiPart {
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiContent {
+    parts: Vec<GeminiPart>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiCandidate {
+    content: GeminiContent,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiResponse {
+    candidates: Vec<GeminiCandidate>,
+}
+
+/// The `Executor` drives invocations against the chat endpoint of the provider
+/// selected by [`PerExecutor::provider`] (OpenAI chat completions by default),
+/// using the options carried by [`PerExecutor`] and [`PerInvocation`].
+pub struct Executor {
+    client: reqwest::Client,
+    options: PerExecutor,
+}
+
+impl Executor {
+    /// Creates a new `Executor` using the given `PerExecutor` options.
+    pub fn new(options: PerExecutor) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            options,
+        }
+    }
+
+    /// Computes the `max_tokens` to send for a given prompt.
+    ///
+    /// For [`Provider::OpenAI`], this is the caller's explicit
+    /// [`PerInvocation::max_tokens`] if set, otherwise the model's
+    /// [`Model::context_size`] minus [`Model::count_tokens`] of the prompt. Either
+    /// way the result is clamped to the remaining room in the context window, so
+    /// an explicit `max_tokens` that would push `prompt_tokens + max_tokens` past
+    /// [`Model::context_size`] can't slip through and get rejected by the API.
+    ///
+    /// [`Model::context_size`] and [`Model::count_tokens`] are calibrated for
+    /// OpenAI's models and `cl100k_base` tokenizer, so for other providers a
+    /// [`Model::Other`] model id (e.g. a `claude-*` or `gemini-*` name) would
+    /// report a misleading `DEFAULT_CONTEXT_SIZE`-based window. Clamping against
+    /// that for non-OpenAI providers would silently truncate completions, so
+    /// those providers get the caller's explicit `max_tokens` unclamped, falling
+    /// back to the model's (best-effort) `context_size` only when unset.
+    pub fn resolve_max_tokens(model: &Model, prompt: &str, options: &PerInvocation, provider: Provider) -> usize {
+        if provider != Provider::OpenAI {
+            return options.max_tokens.unwrap_or_else(|| model.context_size());
+        }
+        let remaining = model.context_size().saturating_sub(model.count_tokens(prompt));
+        match options.max_tokens {
+            Some(max_tokens) => max_tokens.min(remaining),
+            None => remaining,
+        }
+    }
+
+    /// Builds the JSON request body shared by [`Executor::execute`] and
+    /// [`Executor::stream_text`]: the serialized `options` (model, tools,
+    /// sampling parameters, ...) plus the resolved `max_tokens` and the
+    /// single-turn `messages` array for `prompt`.
+    fn request_body(prompt: &str, options: &PerInvocation, stream: bool) -> Value {
+        let model = options.model.clone().unwrap_or_default();
+        let max_tokens = Self::resolve_max_tokens(&model, prompt, options, Provider::OpenAI);
+
+        let mut body = serde_json::to_value(options).unwrap_or_else(|_| Value::Object(Default::default()));
+        if let Value::Object(ref mut map) = body {
+            map.insert("model".to_string(), Value::from(model.to_string()));
+            map.insert("stream".to_string(), Value::Bool(stream));
+            map.insert("max_tokens".to_string(), Value::from(max_tokens));
+            map.insert(
+                "messages".to_string(),
+                serde_json::json!([{ "role": "user", "content": prompt }]),
+            );
+        }
+        body
+    }
+
+    /// Builds the authenticated request for the OpenAI chat-completions wire format.
+    /// Honors [`PerExecutor::api_base`] so the executor can target self-hosted or
+    /// proxy-compatible (e.g. Azure) OpenAI deployments instead of the public API.
+    fn openai_request(&self, body: &Value) -> reqwest::RequestBuilder {
+        let url = match self.options.api_base.as_deref() {
+            Some(api_base) => format!("{}/chat/completions", api_base.trim_end_matches('/')),
+            None => OPENAI_CHAT_COMPLETIONS_URL.to_string(),
+        };
+        let mut request = self.client.post(url).json(body);
+        if let Some(api_key) = &self.options.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        if let Some(organization_id) = &self.options.organization_id {
+            request = request.header("OpenAI-Organization", organization_id);
+        }
+        request
+    }
+
+    /// Builds the authenticated request for the Anthropic Messages API wire format.
+    fn anthropic_request(&self, model: &Model, prompt: &str, max_tokens: usize) -> reqwest::RequestBuilder {
+        let body = serde_json::json!({
+            "model": model.to_string(),
+            "max_tokens": max_tokens,
+            "messages": [{ "role": "user", "content": prompt }],
+        });
+        let mut request = self
+            .client
+            .post(ANTHROPIC_MESSAGES_URL)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&body);
+        if let Some(api_key) = &self.options.api_key {
+            request = request.header("x-api-key", api_key);
+        }
+        request
+    }
+
+    /// Builds the authenticated request for the Gemini `generateContent` wire format.
+    fn gemini_request(&self, model: &Model, prompt: &str, max_tokens: usize) -> reqwest::RequestBuilder {
+        let url = format!("{GEMINI_API_BASE}/{}:generateContent", model.to_string());
+        let body = serde_json::json!({
+            "contents": [{ "parts": [{ "text": prompt }] }],
+            "generationConfig": { "maxOutputTokens": max_tokens },
+        });
+        let mut request = self.client.post(url).json(&body);
+        if let Some(api_key) = &self.options.api_key {
+            request = request.query(&[("key", api_key)]);
+        }
+        request
+    }
+
+    /// Executes the given prompt and returns the buffered completion: either the
+    /// model's text reply, or, if it chose to invoke one of the `tools`
+    /// registered on `options`, an [`Output::ToolCall`] carrying the requested
+    /// function name and its raw JSON arguments for the caller to dispatch.
+    ///
+    /// The request is routed to whichever API [`PerExecutor::provider`] selects;
+    /// tool calling and sampling parameters beyond `max_tokens` are currently only
+    /// translated for the OpenAI wire format.
+    pub async fn execute(&self, prompt: &str, options: &PerInvocation) -> Result<Output, ExecutorError> {
+        let model = options.model.clone().unwrap_or_default();
+        let max_tokens = Self::resolve_max_tokens(&model, prompt, options, self.options.provider);
+
+        match self.options.provider {
+            Provider::OpenAI => {
+                let body = Self::request_body(prompt, options, false);
+                let response = self.openai_request(&body).send().await?.error_for_status()?;
+                let response: CompletionResponse = response.json().await?;
+                Ok(extract_openai_output(response))
+            }
+            Provider::Anthropic => {
+                let response = self
+                    .anthropic_request(&model, prompt, max_tokens)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                let response: AnthropicResponse = response.json().await?;
+                Ok(extract_anthropic_output(response))
+            }
+            Provider::Gemini => {
+                let response = self
+                    .gemini_request(&model, prompt, max_tokens)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                let response: GeminiResponse = response.json().await?;
+                Ok(extract_gemini_output(response))
+            }
+        }
+    }
+
+    /// Executes the given prompt and streams the completion back token by token.
+    ///
+    /// This sends `"stream": true` in the request body, parses the `data:` lines of
+    /// the Server-Sent-Events response, and yields each `choices[].delta.content`
+    /// fragment as it arrives, terminating on the `[DONE]` sentinel. Streaming is
+    /// controlled entirely by which method you call — `stream_text` vs.
+    /// [`Executor::execute`] — rather than by a `stream` field on
+    /// [`PerInvocation`], so it isn't possible to construct invocation options
+    /// that disagree with the method used to send them.
+    ///
+    /// Only the OpenAI provider is supported so far; other providers return
+    /// [`ExecutorError::UnsupportedForProvider`].
+    pub async fn stream_text(
+        &self,
+        prompt: &str,
+        options: &PerInvocation,
+    ) -> Result<impl Stream<Item = Result<String, ExecutorError>>, ExecutorError> {
+        if self.options.provider != Provider::OpenAI {
+            return Err(ExecutorError::UnsupportedForProvider(self.options.provider));
+        }
+        let body = Self::request_body(prompt, options, true);
+        let response = self.openai_request(&body).send().await?.error_for_status()?;
+        let byte_stream = response.bytes_stream();
+
+        let mut buffer: Vec<u8> = Vec::new();
+        Ok(byte_stream
+            .flat_map(move |chunk| {
+                let outcomes: Vec<LineOutcome> = match chunk {
+                    Ok(chunk) => {
+                        // Only complete lines are parsed; a trailing partial line waits for the next chunk.
+                        buffer.extend_from_slice(&chunk);
+                        let end = buffer.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+                        let complete: Vec<u8> = buffer.drain(..end).collect();
+                        String::from_utf8_lossy(&complete).lines().map(parse_sse_line).collect()
+                    }
+                    Err(err) => vec![LineOutcome::Data(Err(err.into()))],
+                };
+                futures::stream::iter(outcomes)
+            })
+            .take_while(|outcome| futures::future::ready(!matches!(outcome, LineOutcome::Done)))
+            .filter_map(|outcome| async move {
+                match outcome {
+                    LineOutcome::Data(result) => Some(result),
+                    _ => None,
+                }
+            }))
+    }
+}
+
+/// Turns an OpenAI `CompletionResponse` into an [`Output`]: a tool call if the
+/// model requested one, otherwise its plain-text reply.
+fn extract_openai_output(response: CompletionResponse) -> Output {
+    let message = response
+        .choices
+        .into_iter()
+        .next()
+        .map(|choice| choice.message)
+        .unwrap_or(Message {
+            content: None,
+            tool_calls: Vec::new(),
+        });
+
+    if let Some(tool_call) = message.tool_calls.into_iter().next() {
+        let arguments = serde_json::from_str(&tool_call.function.arguments).unwrap_or(Value::Null);
+        return Output::ToolCall {
+            name: tool_call.function.name,
+            arguments,
+        };
+    }
+
+    Output::Text(message.content.unwrap_or_default())
+}
+
+/// Turns an Anthropic Messages API response into an [`Output::Text`].
+fn extract_anthropic_output(response: AnthropicResponse) -> Output {
+    let text = response
+        .content
+        .into_iter()
+        .find_map(|block| block.text)
+        .unwrap_or_default();
+    Output::Text(text)
+}
+
+/// Turns a Gemini `generateContent` response into an [`Output::Text`].
+fn extract_gemini_output(response: GeminiResponse) -> Output {
+    let text = response
+        .candidates
+        .into_iter()
+        .next()
+        .and_then(|candidate| candidate.content.parts.into_iter().find_map(|part| part.text))
+        .unwrap_or_default();
+    Output::Text(text)
+}
+
+/// The result of inspecting a single line of a chat-completions SSE stream.
+enum LineOutcome {
+    /// A `data:` line carrying a (possibly empty) content fragment, or a parse error.
+    Data(Result<String, ExecutorError>),
+    /// The terminating `[DONE]` sentinel; the caller should stop reading.
+    Done,
+    /// A line that isn't a `data:` event, e.g. blank lines or `event:` lines.
+    Skip,
+}
+
+/// Parses a single line of a chat-completions SSE stream into a [`LineOutcome`].
+fn parse_sse_line(line: &str) -> LineOutcome {
+    let Some(data) = line.strip_prefix("data: ") else {
+        return LineOutcome::Skip;
+    };
+    if data == "[DONE]" {
+        return LineOutcome::Done;
+    }
+    LineOutcome::Data(
+        serde_json::from_str::<Chunk>(data)
+            .map_err(ExecutorError::from)
+            .map(|chunk| {
+                chunk
+                    .choices
+                    .into_iter()
+                    .next()
+                    .and_then(|choice| choice.delta.content)
+                    .unwrap_or_default()
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sse_line_extracts_delta_content() {
+        let line = r#"data: {"choices":[{"delta":{"content":"Hel"}}]}"#;
+        match parse_sse_line(line) {
+            LineOutcome::Data(Ok(content)) => assert_eq!(content, "Hel"),
+            _ => panic!("expected LineOutcome::Data(Ok(\"Hel\"))"),
+        }
+    }
+
+    #[test]
+    fn parse_sse_line_stops_on_done_sentinel() {
+        assert!(matches!(parse_sse_line("data: [DONE]"), LineOutcome::Done));
+    }
+
+    #[test]
+    fn parse_sse_line_skips_non_data_lines() {
+        assert!(matches!(parse_sse_line(""), LineOutcome::Skip));
+        assert!(matches!(parse_sse_line("event: message"), LineOutcome::Skip));
+    }
+
+    #[test]
+    fn parse_sse_line_surfaces_malformed_json() {
+        assert!(matches!(parse_sse_line("data: not json"), LineOutcome::Data(Err(_))));
+    }
+
+    #[test]
+    fn extract_openai_output_returns_text_when_no_tool_call() {
+        let response: CompletionResponse = serde_json::from_str(
+            r#"{"choices":[{"message":{"content":"Hello there"}}]}"#,
+        )
+        .unwrap();
+        assert!(matches!(extract_openai_output(response), Output::Text(text) if text == "Hello there"));
+    }
+
+    #[test]
+    fn extract_openai_output_returns_tool_call_when_model_requests_one() {
+        let response: CompletionResponse = serde_json::from_str(
+            r#"{"choices":[{"message":{"content":null,"tool_calls":[
+                {"function":{"name":"get_weather","arguments":"{\"city\":\"Paris\"}"}}
+            ]}}]}"#,
+        )
+        .unwrap();
+        match extract_openai_output(response) {
+            Output::ToolCall { name, arguments } => {
+                assert_eq!(name, "get_weather");
+                assert_eq!(arguments, serde_json::json!({ "city": "Paris" }));
+            }
+            other => panic!("expected Output::ToolCall, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn resolve_max_tokens_defaults_to_remaining_context_when_unset() {
+        let model = Model::ChatGPT3_5Turbo;
+        let prompt = "hi";
+        let options = PerInvocation::new();
+        let expected = model.context_size() - model.count_tokens(prompt);
+        assert_eq!(
+            Executor::resolve_max_tokens(&model, prompt, &options, Provider::OpenAI),
+            expected
+        );
+    }
+
+    #[test]
+    fn resolve_max_tokens_passes_through_an_explicit_value_that_fits() {
+        let model = Model::ChatGPT3_5Turbo;
+        let options = PerInvocation::new().with_max_tokens(16);
+        assert_eq!(
+            Executor::resolve_max_tokens(&model, "hi", &options, Provider::OpenAI),
+            16
+        );
+    }
+
+    #[test]
+    fn resolve_max_tokens_clamps_an_explicit_value_that_overflows_the_context_window() {
+        let model = Model::ChatGPT3_5Turbo;
+        let prompt = "hi";
+        let options = PerInvocation::new().with_max_tokens(usize::MAX);
+        let expected = model.context_size() - model.count_tokens(prompt);
+        assert_eq!(
+            Executor::resolve_max_tokens(&model, prompt, &options, Provider::OpenAI),
+            expected
+        );
+    }
+
+    #[test]
+    fn resolve_max_tokens_does_not_clamp_explicit_values_for_non_openai_providers() {
+        // `Model::Other`'s context_size() is a generic OpenAI-calibrated fallback,
+        // not the real window of e.g. a Claude or Gemini model, so non-OpenAI
+        // providers must not have their explicit max_tokens clamped against it.
+        let model = Model::Other("claude-3-opus-20240229".to_string());
+        let options = PerInvocation::new().with_max_tokens(100_000);
+        assert_eq!(
+            Executor::resolve_max_tokens(&model, "hi", &options, Provider::Anthropic),
+            100_000
+        );
+    }
+
+    #[test]
+    fn resolve_max_tokens_falls_back_to_context_size_for_non_openai_providers_when_unset() {
+        let model = Model::Other("gemini-1.5-pro".to_string());
+        let options = PerInvocation::new();
+        assert_eq!(
+            Executor::resolve_max_tokens(&model, "hi", &options, Provider::Gemini),
+            model.context_size()
+        );
+    }
+
+    #[test]
+    fn gemini_request_sets_max_output_tokens() {
+        let executor = Executor::new(PerExecutor::default());
+        let model = Model::Other("gemini-1.5-pro".to_string());
+        let request = executor.gemini_request(&model, "hi", 123).build().unwrap();
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 123);
+    }
+
+    #[test]
+    fn extract_anthropic_output_reads_the_first_text_block() {
+        let response: AnthropicResponse = serde_json::from_str(
+            r#"{"content":[{"type":"text","text":"Bonjour"}]}"#,
+        )
+        .unwrap();
+        assert!(matches!(extract_anthropic_output(response), Output::Text(text) if text == "Bonjour"));
+    }
+
+    #[test]
+    fn extract_gemini_output_reads_the_first_candidate_part() {
+        let response: GeminiResponse = serde_json::from_str(
+            r#"{"candidates":[{"content":{"parts":[{"text":"Hola"}]}}]}"#,
+        )
+        .unwrap();
+        assert!(matches!(extract_gemini_output(response), Output::Text(text) if text == "Hola"));
+    }
+
+    #[test]
+    fn stream_text_rejects_non_openai_providers_without_sending_a_request() {
+        let executor = Executor::new(PerExecutor {
+            provider: Provider::Anthropic,
+            ..PerExecutor::default()
+        });
+        let result = futures::executor::block_on(executor.stream_text("hi", &PerInvocation::new()));
+        assert!(matches!(
+            result.err(),
+            Some(ExecutorError::UnsupportedForProvider(Provider::Anthropic))
+        ));
+    }
+}
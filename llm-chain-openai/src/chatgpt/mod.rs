@@ -0,0 +1,7 @@
+mod executor;
+mod options;
+mod output;
+
+pub use executor::{Executor, ExecutorError};
+pub use options::{LogitBias, Model, PerExecutor, PerExecutorError, PerInvocation, Provider, Tool, ToolChoice};
+pub use output::Output;
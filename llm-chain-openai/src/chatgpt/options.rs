@@ -1,5 +1,16 @@
+use std::collections::HashMap;
+use std::path::Path;
+
 use llm_chain::traits;
-use serde::{Deserialize, Serialize};
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use thiserror::Error;
+
+/// A map from token id to a bias in `[-100, 100]`, used to up- or down-weight
+/// specific tokens during sampling. A bias of `-100` effectively bans the
+/// token, while `100` makes it near-guaranteed to be picked.
+pub type LogitBias = HashMap<u32, f32>;
 
 /// The `Model` enum represents the available ChatGPT models that you can use through the OpenAI API. These models have different capabilities and performance characteristics, allowing you to choose the one that best suits your needs.
 ///
@@ -30,6 +41,50 @@ impl Default for Model {
     }
 }
 
+/// The context window size (in tokens) reported for every [`Model::Other`] model.
+/// It is a fixed value; there is no way to set a different window per model.
+const DEFAULT_CONTEXT_SIZE: usize = 4096;
+
+impl Model {
+    /// Returns the size of the model's context window, in tokens. This is the
+    /// combined budget for the prompt and the completion; the OpenAI API rejects
+    /// requests whose `prompt_tokens + max_tokens` exceeds it.
+    ///
+    /// `Other` models report [`DEFAULT_CONTEXT_SIZE`] since their true limit isn't
+    /// known to this crate. For [`Provider::OpenAI`] the executor clamps even an
+    /// explicit `max_tokens` to this window, so a custom OpenAI model with a
+    /// larger window is still limited to it.
+    pub fn context_size(&self) -> usize {
+        match self {
+            Self::ChatGPT3_5Turbo => 4096,
+            Self::GPT4 => 8192,
+            Self::Other(_) => DEFAULT_CONTEXT_SIZE,
+        }
+    }
+
+    /// Counts how many tokens `text` would take up for this model, using the
+    /// `cl100k_base` BPE encoding shared by the GPT-3.5/4 family. Use this to
+    /// measure prompt size, stay under [`Model::context_size`], and estimate
+    /// cost before sending a request.
+    ///
+    /// `tiktoken_rs::cl100k_base` lazily fetches its BPE rank file the first
+    /// time it's used, so this falls back to a rough (~4 characters per token)
+    /// estimate rather than panicking if that load fails, e.g. offline or in a
+    /// sandboxed environment.
+    pub fn count_tokens(&self, text: &str) -> usize {
+        match tiktoken_rs::cl100k_base() {
+            Ok(bpe) => bpe.encode_with_special_tokens(text).len(),
+            Err(_) => text.len() / 4,
+        }
+    }
+
+    /// Returns the built-in `Model` variants this crate knows about. `Other`
+    /// isn't included since it names a model outside this list.
+    pub fn list_models() -> Vec<Model> {
+        vec![Self::ChatGPT3_5Turbo, Self::GPT4]
+    }
+}
+
 /// The `Model` enum implements the `ToString` trait, allowing you to easily convert it to a string.
 impl ToString for Model {
     fn to_string(&self) -> String {
@@ -52,11 +107,162 @@ impl From<String> for Model {
     }
 }
 
+/// Describes a single callable tool (an OpenAI "function") that the model may
+/// invoke instead of replying directly.
+#[derive(Debug, Clone)]
+pub struct Tool {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+impl Serialize for Tool {
+    /// Serializes to OpenAI's `tools` entry shape, `{"type":"function","function":{...}}`,
+    /// not the flat `{name, description, parameters}` struct.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": self.name,
+                "description": self.description,
+                "parameters": self.parameters,
+            },
+        })
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Tool {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        let function = value
+            .get("function")
+            .ok_or_else(|| serde::de::Error::custom("expected a `function` tool object"))?;
+        let name = function
+            .get("name")
+            .and_then(|n| n.as_str())
+            .ok_or_else(|| serde::de::Error::custom("tool function is missing a `name`"))?
+            .to_string();
+        let description = function
+            .get("description")
+            .and_then(|d| d.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let parameters = function.get("parameters").cloned().unwrap_or(Value::Null);
+        Ok(Self {
+            name,
+            description,
+            parameters,
+        })
+    }
+}
+
+impl Tool {
+    /// Builds a `Tool` whose JSON-schema `parameters` are derived from `T` via
+    /// `schemars`, so callers only need to define a plain Rust struct for the
+    /// tool's arguments instead of hand-writing a JSON schema.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use llm_chain_openai::chatgpt::Tool;
+    /// use schemars::JsonSchema;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize, JsonSchema)]
+    /// struct GetWeather {
+    ///     city: String,
+    /// }
+    ///
+    /// let tool = Tool::from_schema::<GetWeather>("get_weather", "Looks up the current weather for a city");
+    /// ```
+    pub fn from_schema<T: JsonSchema>(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters: serde_json::to_value(schema_for!(T)).unwrap_or(Value::Null),
+        }
+    }
+}
+
+/// Controls whether, and which, tool the model is allowed to call.
+#[derive(Debug, Clone)]
+pub enum ToolChoice {
+    /// Let the model decide whether to call a tool.
+    Auto,
+    /// Never call a tool.
+    None,
+    /// Force a call to the named tool.
+    Named(String),
+}
+
+impl Serialize for ToolChoice {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::Auto => serializer.serialize_str("auto"),
+            Self::None => serializer.serialize_str("none"),
+            Self::Named(name) => serde_json::json!({
+                "type": "function",
+                "function": { "name": name },
+            })
+            .serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ToolChoice {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Value::deserialize(deserializer)? {
+            Value::String(s) if s == "auto" => Ok(Self::Auto),
+            Value::String(s) if s == "none" => Ok(Self::None),
+            Value::Object(map) => map
+                .get("function")
+                .and_then(|f| f.get("name"))
+                .and_then(|n| n.as_str())
+                .map(|name| Self::Named(name.to_string()))
+                .ok_or_else(|| serde::de::Error::custom("expected a `function.name` tool_choice object")),
+            other => Err(serde::de::Error::custom(format!(
+                "invalid tool_choice value: {other}"
+            ))),
+        }
+    }
+}
+
 /// The `PerInvocation` struct contains options that can be specified for each ChatGPT invocation.
-/// Currently, it only supports specifying a `Model`.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PerInvocation {
     pub(crate) model: Option<Model>,
+    pub(crate) max_tokens: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) tools: Vec<Tool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) tool_choice: Option<ToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) logit_bias: Option<LogitBias>,
 }
 
 impl PerInvocation {
@@ -71,15 +277,264 @@ impl PerInvocation {
             ..self
         }
     }
+    /// Sets an explicit `max_tokens` budget for the completion. When left unset,
+    /// the executor computes a safe default from the model's
+    /// [`Model::context_size`] and the size of the prompt; see
+    /// [`super::executor::Executor::resolve_max_tokens`].
+    pub fn with_max_tokens(self: Self, max_tokens: usize) -> Self {
+        Self {
+            max_tokens: Some(max_tokens),
+            ..self
+        }
+    }
+    /// Registers a tool the model may call. Registering at least one tool makes
+    /// the executor send `tools` (and `tool_choice`, if set via
+    /// [`PerInvocation::with_tool_choice`]) in the request body; see
+    /// [`super::output::Output::ToolCall`] for how calls are surfaced back.
+    pub fn with_tool(mut self: Self, tool: Tool) -> Self {
+        self.tools.push(tool);
+        self
+    }
+    /// Sets the `tool_choice` mode, controlling whether and which tool the model
+    /// must call. Only meaningful once at least one tool has been registered via
+    /// [`PerInvocation::with_tool`].
+    pub fn with_tool_choice(self: Self, tool_choice: ToolChoice) -> Self {
+        Self {
+            tool_choice: Some(tool_choice),
+            ..self
+        }
+    }
+    /// Sets the sampling `temperature` (higher is more random).
+    pub fn with_temperature(self: Self, temperature: f32) -> Self {
+        Self {
+            temperature: Some(temperature),
+            ..self
+        }
+    }
+    /// Sets `top_p` for nucleus sampling, as an alternative to `temperature`.
+    pub fn with_top_p(self: Self, top_p: f32) -> Self {
+        Self {
+            top_p: Some(top_p),
+            ..self
+        }
+    }
+    /// Sets how many completion choices to generate for the prompt.
+    pub fn with_n(self: Self, n: u32) -> Self {
+        Self { n: Some(n), ..self }
+    }
+    /// Sets up to four sequences where the API will stop generating further tokens.
+    pub fn with_stop(self: Self, stop: Vec<String>) -> Self {
+        Self {
+            stop: Some(stop),
+            ..self
+        }
+    }
+    /// Sets the `presence_penalty`, which penalizes tokens that have already
+    /// appeared at all, encouraging the model to talk about new topics.
+    pub fn with_presence_penalty(self: Self, presence_penalty: f32) -> Self {
+        Self {
+            presence_penalty: Some(presence_penalty),
+            ..self
+        }
+    }
+    /// Sets the `frequency_penalty`, which penalizes tokens in proportion to how
+    /// often they've already appeared, discouraging verbatim repetition.
+    pub fn with_frequency_penalty(self: Self, frequency_penalty: f32) -> Self {
+        Self {
+            frequency_penalty: Some(frequency_penalty),
+            ..self
+        }
+    }
+    /// Sets a [`LogitBias`] map to up- or down-weight specific tokens during sampling.
+    pub fn with_logit_bias(self: Self, logit_bias: LogitBias) -> Self {
+        Self {
+            logit_bias: Some(logit_bias),
+            ..self
+        }
+    }
 }
 
 impl traits::Options for PerInvocation {}
 
+/// Which hosted LLM API an [`super::executor::Executor`] talks to. Despite the
+/// `chatgpt` module's name, the executor isn't tied to OpenAI: it translates
+/// the same unified chat request into whichever provider's wire format
+/// `Provider` selects. There's no auto-detection from the model name — set
+/// `Provider::Anthropic`/`Provider::Gemini` explicitly alongside a
+/// [`Model::Other`] naming the underlying model (e.g. a `claude-*` or
+/// `gemini-*` model id).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Provider {
+    OpenAI,
+    Anthropic,
+    Gemini,
+}
+
+impl Default for Provider {
+    fn default() -> Self {
+        Self::OpenAI
+    }
+}
+
 /// The `PerExecutor` struct contains options that can be specified for the ChatGPT `Executor`.
-/// Currently, it only supports specifying an `api_key`.
+/// It supports specifying an `api_key`, which hosted LLM API to target via `provider`, an
+/// optional `organization_id`, and an optional `api_base` for pointing at self-hosted or
+/// proxy-compatible endpoints (e.g. Azure OpenAI deployments).
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PerExecutor {
     pub api_key: Option<String>,
+    #[serde(default)]
+    pub provider: Provider,
+    pub organization_id: Option<String>,
+    pub api_base: Option<String>,
+}
+
+/// Errors that can occur while loading a [`PerExecutor`] from the environment or a config file.
+#[derive(Debug, Error)]
+pub enum PerExecutorError {
+    #[error("missing required environment variable {0}")]
+    MissingEnvVar(&'static str),
+    #[error("failed to read config file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("config file has an unsupported extension: {0:?}")]
+    UnsupportedExtension(Option<String>),
+    #[error("failed to parse TOML config: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("failed to parse JSON config: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("failed to parse YAML config: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+impl PerExecutor {
+    /// Builds a `PerExecutor` from the standard OpenAI environment variables:
+    /// `OPENAI_API_KEY` (required), `OPENAI_ORG`, and `OPENAI_API_BASE`. Loading
+    /// `.env` files first, if desired, is the caller's responsibility (e.g. via
+    /// the `dotenvy` crate) so this crate doesn't impose dotenv plumbing on
+    /// callers who configure the environment some other way.
+    pub fn from_env() -> Result<Self, PerExecutorError> {
+        let api_key = std::env::var("OPENAI_API_KEY")
+            .map_err(|_| PerExecutorError::MissingEnvVar("OPENAI_API_KEY"))?;
+        Ok(Self {
+            api_key: Some(api_key),
+            organization_id: std::env::var("OPENAI_ORG").ok(),
+            api_base: std::env::var("OPENAI_API_BASE").ok(),
+            ..Self::default()
+        })
+    }
+
+    /// Builds a `PerExecutor` by deserializing a TOML, JSON, or YAML config file,
+    /// chosen by the file's extension (`.toml`, `.json`, or `.yaml`/`.yml`).
+    pub fn from_config_file(path: impl AsRef<Path>) -> Result<Self, PerExecutorError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Ok(toml::from_str(&contents)?),
+            Some("json") => Ok(serde_json::from_str(&contents)?),
+            Some("yaml" | "yml") => Ok(serde_yaml::from_str(&contents)?),
+            other => Err(PerExecutorError::UnsupportedExtension(other.map(String::from))),
+        }
+    }
 }
 
 impl traits::Options for PerExecutor {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn per_invocation_default_omits_unset_sampling_fields() {
+        let json = serde_json::to_value(PerInvocation::new()).unwrap();
+        let fields = [
+            "tool_choice",
+            "temperature",
+            "top_p",
+            "n",
+            "stop",
+            "presence_penalty",
+            "frequency_penalty",
+            "logit_bias",
+        ];
+        for field in fields {
+            assert!(
+                !json.as_object().unwrap().contains_key(field),
+                "expected `{field}` to be omitted when unset, got {json}"
+            );
+        }
+    }
+
+    #[test]
+    fn per_invocation_serializes_only_the_sampling_fields_that_are_set() {
+        let invocation = PerInvocation::new().with_temperature(0.7).with_n(2);
+        let json = serde_json::to_value(invocation).unwrap();
+        assert_eq!(json["temperature"], serde_json::json!(0.7));
+        assert_eq!(json["n"], serde_json::json!(2));
+        assert!(json.get("top_p").is_none());
+        assert!(json.get("stop").is_none());
+    }
+
+    #[test]
+    fn from_env_requires_api_key_and_reads_optional_variables() {
+        std::env::remove_var("OPENAI_API_KEY");
+        std::env::remove_var("OPENAI_ORG");
+        std::env::remove_var("OPENAI_API_BASE");
+        assert!(matches!(
+            PerExecutor::from_env(),
+            Err(PerExecutorError::MissingEnvVar("OPENAI_API_KEY"))
+        ));
+
+        std::env::set_var("OPENAI_API_KEY", "sk-test");
+        std::env::set_var("OPENAI_ORG", "org-test");
+        std::env::set_var("OPENAI_API_BASE", "https://example.test/v1");
+        let executor = PerExecutor::from_env().unwrap();
+        assert_eq!(executor.api_key.as_deref(), Some("sk-test"));
+        assert_eq!(executor.organization_id.as_deref(), Some("org-test"));
+        assert_eq!(executor.api_base.as_deref(), Some("https://example.test/v1"));
+        assert_eq!(executor.provider, Provider::OpenAI);
+
+        std::env::remove_var("OPENAI_API_KEY");
+        std::env::remove_var("OPENAI_ORG");
+        std::env::remove_var("OPENAI_API_BASE");
+    }
+
+    #[test]
+    fn from_config_file_tolerates_a_missing_provider_key() {
+        let dir = std::env::temp_dir();
+        let path = dir.join("llm_chain_openai_per_executor_test.toml");
+        std::fs::write(&path, "api_key = \"sk-test\"\n").unwrap();
+
+        let executor = PerExecutor::from_config_file(&path).unwrap();
+        assert_eq!(executor.api_key.as_deref(), Some("sk-test"));
+        assert_eq!(executor.provider, Provider::OpenAI);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn from_config_file_rejects_an_unknown_extension() {
+        let dir = std::env::temp_dir();
+        let path = dir.join("llm_chain_openai_per_executor_test.ini");
+        std::fs::write(&path, "api_key = sk-test\n").unwrap();
+
+        assert!(matches!(
+            PerExecutor::from_config_file(&path),
+            Err(PerExecutorError::UnsupportedExtension(Some(ext))) if ext == "ini"
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn count_tokens_is_positive_for_nonempty_text() {
+        assert!(Model::GPT4.count_tokens("hello, world!") > 0);
+    }
+
+    #[test]
+    fn list_models_contains_the_built_in_variants_but_not_other() {
+        let models = Model::list_models();
+        assert!(models.iter().any(|m| matches!(m, Model::ChatGPT3_5Turbo)));
+        assert!(models.iter().any(|m| matches!(m, Model::GPT4)));
+        assert!(!models.iter().any(|m| matches!(m, Model::Other(_))));
+    }
+}
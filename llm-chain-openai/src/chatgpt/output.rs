@@ -0,0 +1,13 @@
+use serde_json::Value;
+
+/// The result of a single ChatGPT invocation.
+#[derive(Debug, Clone)]
+pub enum Output {
+    /// A plain-text completion.
+    Text(String),
+    /// The model chose to invoke one of the tools registered on the
+    /// `PerInvocation` via [`super::options::Tool`] instead of replying
+    /// directly. Dispatch `name` with the parsed `arguments` and feed the
+    /// result back as a follow-up message to continue the conversation.
+    ToolCall { name: String, arguments: Value },
+}